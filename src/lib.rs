@@ -21,6 +21,18 @@
 //! # }
 //! ```
 //!
+//! If the smoothing interval should stay stable regardless of frame rate, use
+//! `with_window_duration` instead to keep all ticks within the last `N` seconds rather than the
+//! last `N` frames.
+//!
+//! ```rust
+//! # use fps_ticker::Fps;
+//! # use std::time::Duration;
+//! # fn main() {
+//! let fps = Fps::with_window_duration(Duration::from_secs(1));
+//! # }
+//! ```
+//!
 //! Call `tick` once per frame at the point at which you wish to measure the rate.  This samples
 //! the duration since the last tick, adds it to the window, removes the oldest duration from the
 //! window if necessary and re-calculates the average, minimum and maximum rate over the resulting
@@ -46,25 +58,118 @@
 //! fps.max();
 //! # }
 //! ```
+//!
+//! Enabling the `hdrhistogram` feature also records every frame duration into a latency
+//! histogram, exposing `percentile_low` for "1% low" / "0.1% low" style stutter metrics.
+//!
+//! To both measure and pace a loop to a target frame rate, construct with `with_target` and call
+//! `tick_and_limit` instead of `tick`.
+//!
+//! ```rust
+//! # use fps_ticker::Fps;
+//! # fn main() {
+//! let fps = Fps::with_target(60, 60.0);
+//! fps.tick_and_limit();
+//! # }
+//! ```
+//!
+//! To log a readout at most once per second regardless of frame rate, install a throttled
+//! callback with `report_every` before ticking.
+//!
+//! ```rust
+//! # use fps_ticker::Fps;
+//! # use std::time::Duration;
+//! # fn main() {
+//! let fps = Fps::default();
+//! fps.report_every(Duration::from_secs(1), |fps| {
+//!     println!("avg: {}, min: {}, max: {}", fps.avg(), fps.min(), fps.max());
+//! });
+//! fps.tick();
+//! # }
+//! ```
+//!
+//! For a quick terminal or debug-overlay readout, render the recent frame-rate history as a
+//! one-line sparkline.
+//!
+//! ```rust
+//! # use fps_ticker::Fps;
+//! # fn main() {
+//! # let fps = Fps::default();
+//! # fps.tick();
+//! println!("{}", fps.sparkline(80));
+//! # }
+//! ```
 
 use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 /// Simple type for tracking frames-per-second.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Fps {
-    window_len: usize,
+    window: Window,
+    /// The frame rate `tick_and_limit` should pace the calling loop to, if any.
+    target_fps: Option<f64>,
+    /// The throttled reporting hook installed via `report_every`, if any.
+    ///
+    /// Held behind an `Rc` rather than inlined so that `Fps` can still be `Clone`: a
+    /// `Box<dyn FnMut(&Fps)>` itself isn't cloneable, and cloning it per-`Fps` would silently
+    /// break the `Clone` impl that existed before `report_every` was added. Clones of an `Fps`
+    /// therefore share the same installed callback and its throttling state, unlike `window`,
+    /// `target_fps` and `inner`, which are deep-copied per clone.
+    report: Rc<RefCell<Option<Report>>>,
     inner: RefCell<Inner>,
 }
 
+impl std::fmt::Debug for Fps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fps")
+            .field("window", &self.window)
+            .field("target_fps", &self.target_fps)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A throttled reporting hook installed via `Fps::report_every`.
+struct Report {
+    interval: Duration,
+    last: Instant,
+    callback: Box<dyn FnMut(&Fps)>,
+}
+
+/// The strategy used to decide which samples remain within the measurement window.
+#[derive(Clone, Debug)]
+enum Window {
+    /// Keep at most this many of the most recent frames, regardless of how long they span.
+    Frames(usize),
+    /// Keep every frame whose timestamp falls within this duration of now.
+    Duration(Duration),
+}
+
 #[derive(Clone, Debug)]
 struct Inner {
-    window: VecDeque<Duration>,
+    window: VecDeque<(Instant, Duration, u64)>,
+    /// Running total of every duration currently in `window`, kept in sync incrementally so
+    /// `calc_avg` never has to re-sum the window.
+    sum: Duration,
+    /// Decreasing deque of `(seq, duration)` pairs; the front always holds the longest (i.e.
+    /// slowest) frame duration currently in the window.
+    max_duration: VecDeque<(u64, Duration)>,
+    /// Increasing deque of `(seq, duration)` pairs; the front always holds the shortest (i.e.
+    /// fastest) frame duration currently in the window.
+    min_duration: VecDeque<(u64, Duration)>,
+    /// Monotonically increasing id assigned to each tick, used to identify window entries within
+    /// `max_duration`/`min_duration` without their indices shifting on eviction.
+    next_seq: u64,
     last: Instant,
     avg: f64,
     min: f64,
     max: f64,
+    /// Histogram of every frame duration in nanoseconds, used to derive percentile lows.
+    #[cfg(feature = "hdrhistogram")]
+    histogram: hdrhistogram::Histogram<u64>,
 }
 
 impl Fps {
@@ -75,32 +180,148 @@ impl Fps {
     ///
     /// The larger the window, the "smoother" the FPS.
     pub fn with_window_len(window_len: usize) -> Self {
-        let window = VecDeque::with_capacity(window_len);
-        let last = Instant::now();
-        let (avg, min, max) = (0.0, 0.0, 0.0);
+        Self::with_window(Window::Frames(window_len))
+    }
+
+    /// Create a new `Fps` that measures over a sliding window of time rather than a fixed number
+    /// of frames.
+    ///
+    /// Every tick older than `now - window_duration` is evicted, so the smoothing interval stays
+    /// stable regardless of the underlying frame rate.
+    pub fn with_window_duration(window_duration: Duration) -> Self {
+        Self::with_window(Window::Duration(window_duration))
+    }
+
+    /// Create a new `Fps` with the given window length that also paces `tick_and_limit` to the
+    /// given target frame rate.
+    pub fn with_target(window_len: usize, target_fps: f64) -> Self {
+        let mut fps = Self::with_window(Window::Frames(window_len));
+        fps.target_fps = Some(target_fps);
+        fps
+    }
+
+    fn with_window(window: Window) -> Self {
+        let capacity = match window {
+            Window::Frames(len) => len,
+            Window::Duration(_) => 0,
+        };
         let inner = RefCell::new(Inner {
-            window,
-            last,
-            avg,
-            min,
-            max,
+            window: VecDeque::with_capacity(capacity),
+            sum: Duration::default(),
+            max_duration: VecDeque::with_capacity(capacity),
+            min_duration: VecDeque::with_capacity(capacity),
+            next_seq: 0,
+            last: Instant::now(),
+            avg: 0.0,
+            min: 0.0,
+            max: 0.0,
+            #[cfg(feature = "hdrhistogram")]
+            histogram: hdrhistogram::Histogram::new_with_bounds(1, Duration::from_secs(60).as_nanos() as u64, 3)
+                .expect("invalid histogram bounds"),
         });
-        Fps { window_len, inner }
+        Fps {
+            window,
+            target_fps: None,
+            report: Rc::new(RefCell::new(None)),
+            inner,
+        }
     }
 
     /// Call this once per frame to allow the `Fps` instance to sample the rate internally.
     pub fn tick(&self) {
         let now = Instant::now();
-        let mut inner = self.inner.borrow_mut();
-        let delta = now.duration_since(inner.last);
-        inner.last = now;
-        while inner.window.len() + 1 > self.window_len {
-            inner.window.pop_front();
+        {
+            let mut inner = self.inner.borrow_mut();
+            let delta = now.duration_since(inner.last);
+            inner.last = now;
+            match self.window {
+                Window::Frames(window_len) => {
+                    while inner.window.len() + 1 > window_len {
+                        inner.evict_front();
+                    }
+                }
+                Window::Duration(window_duration) => {
+                    while inner
+                        .window
+                        .front()
+                        .map(|&(t, _, _)| now.duration_since(t) > window_duration)
+                        .unwrap_or(false)
+                    {
+                        inner.evict_front();
+                    }
+                }
+            }
+            inner.push(now, delta);
+            inner.avg = inner.calc_avg();
+            inner.min = inner.calc_min();
+            inner.max = inner.calc_max();
+            #[cfg(feature = "hdrhistogram")]
+            {
+                let nanos = delta.as_nanos().min(u64::MAX as u128) as u64;
+                let _ = inner.histogram.record(nanos.max(1));
+            }
+        }
+        self.fire_report(now);
+    }
+
+    /// Invoke the `report_every` callback, if one is installed and its interval has elapsed.
+    ///
+    /// The `Report` is temporarily taken out of its `RefCell` so the borrow is released before
+    /// the callback runs, since the callback may itself call `tick`, `tick_and_limit` or
+    /// `report_every` on this same `Fps`.
+    fn fire_report(&self, now: Instant) {
+        let mut report = match self.report.borrow_mut().take() {
+            Some(report) => report,
+            None => return,
+        };
+        if now.duration_since(report.last) >= report.interval {
+            report.last = now;
+            (report.callback)(self);
+        }
+        let mut slot = self.report.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(report);
         }
-        inner.window.push_back(delta);
-        inner.avg = inner.calc_avg();
-        inner.min = inner.calc_min();
-        inner.max = inner.calc_max();
+    }
+
+    /// Like `tick`, but also paces the calling loop to the target FPS given to `with_target`.
+    ///
+    /// After sampling the delta as `tick` does, sleeps for whatever's left of the ideal per-frame
+    /// period (`1.0 / target_fps`), spin-yielding through the final sub-millisecond remainder to
+    /// stay accurate despite OS sleep granularity. Returns the duration actually slept, measured
+    /// after the spin-wait completes, or `Duration::default()` if no target was set, `target_fps`
+    /// isn't positive, or the frame already ran over budget.
+    pub fn tick_and_limit(&self) -> Duration {
+        self.tick();
+
+        let target_fps = match self.target_fps {
+            Some(target_fps) if target_fps > 0.0 => target_fps,
+            _ => return Duration::default(),
+        };
+        let period = Duration::from_secs_f64(1.0 / target_fps);
+        let elapsed = self
+            .inner
+            .borrow()
+            .window
+            .back()
+            .map(|&(_, d, _)| d)
+            .unwrap_or_default();
+        let remaining = match period.checked_sub(elapsed) {
+            Some(remaining) if remaining > Duration::default() => remaining,
+            _ => return Duration::default(),
+        };
+
+        const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+        let start = Instant::now();
+        let deadline = start + remaining;
+        if remaining > SPIN_THRESHOLD {
+            std::thread::sleep(remaining - SPIN_THRESHOLD);
+        }
+        while Instant::now() < deadline {
+            std::thread::yield_now();
+        }
+
+        start.elapsed()
     }
 
     /// Retrieve the average frames-per-second at the moment of the last call to `tick`.
@@ -119,32 +340,144 @@ impl Fps {
     pub fn max(&self) -> f64 {
         self.inner.borrow().max
     }
+
+    /// Retrieve the "low" frames-per-second at the given percentile of recorded frame times.
+    ///
+    /// For example, `percentile_low(99.0)` returns the "1% low" FPS, i.e. the frame rate implied
+    /// by the duration at the 99th percentile of worst frame times seen since construction or the
+    /// last call to `reset`. This is a far more meaningful stutter metric than `min`, which is
+    /// dominated by a single outlier frame.
+    #[cfg(feature = "hdrhistogram")]
+    pub fn percentile_low(&self, p: f64) -> f64 {
+        let nanos = self.inner.borrow().histogram.value_at_percentile(p);
+        if nanos == 0 {
+            0.0
+        } else {
+            1.0e9 / nanos as f64
+        }
+    }
+
+    /// Clear the percentile histogram, e.g. between separate measurement runs.
+    #[cfg(feature = "hdrhistogram")]
+    pub fn reset(&self) {
+        self.inner.borrow_mut().histogram.reset();
+    }
+
+    /// Install a callback that `tick` fires at most once per `interval`, regardless of the
+    /// underlying frame rate, passing `self` so the callback can read the current `avg`/`min`/
+    /// `max` snapshot. Useful for driving a log line or on-screen readout roughly once a second
+    /// without printing every single frame.
+    ///
+    /// Replaces any previously installed callback.
+    pub fn report_every(&self, interval: Duration, callback: impl FnMut(&Fps) + 'static) {
+        *self.report.borrow_mut() = Some(Report {
+            interval,
+            last: Instant::now(),
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Render the most recent `width` frames of the window as a one-line text sparkline.
+    ///
+    /// Each sample's instantaneous FPS is scaled linearly between the window's current `min` and
+    /// `max` and mapped onto the eight block glyphs `▁▂▃▄▅▆▇█`, so the tallest bar is the best
+    /// frame and the shortest is the worst. Slots with no sample (because the window currently
+    /// holds fewer than `width` frames) are rendered as leading spaces.
+    pub fn sparkline(&self, width: usize) -> String {
+        const GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+        let inner = self.inner.borrow();
+        let (min, max) = (inner.min, inner.max);
+        let range = max - min;
+        let samples: Vec<f64> = inner
+            .window
+            .iter()
+            .rev()
+            .take(width)
+            .map(|&(_, d, _)| 1.0 / d.as_secs_f64())
+            .collect();
+
+        let mut line = String::with_capacity(width);
+        line.extend(std::iter::repeat_n(' ', width.saturating_sub(samples.len())));
+        for &fps in samples.iter().rev() {
+            let glyph = if range <= 0.0 {
+                GLYPHS[GLYPHS.len() - 1]
+            } else {
+                let t = ((fps - min) / range).clamp(0.0, 1.0);
+                GLYPHS[(t * (GLYPHS.len() - 1) as f64).round() as usize]
+            };
+            line.push(glyph);
+        }
+        line
+    }
 }
 
 impl Inner {
+    /// Push a new tick's duration into the window, updating the running sum and the monotonic
+    /// min/max deques in amortised O(1).
+    fn push(&mut self, now: Instant, delta: Duration) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.sum += delta;
+
+        while self
+            .max_duration
+            .back()
+            .map(|&(_, d)| d <= delta)
+            .unwrap_or(false)
+        {
+            self.max_duration.pop_back();
+        }
+        self.max_duration.push_back((seq, delta));
+
+        while self
+            .min_duration
+            .back()
+            .map(|&(_, d)| d >= delta)
+            .unwrap_or(false)
+        {
+            self.min_duration.pop_back();
+        }
+        self.min_duration.push_back((seq, delta));
+
+        self.window.push_back((now, delta, seq));
+    }
+
+    /// Pop the oldest tick out of the window, keeping the running sum and the monotonic min/max
+    /// deques in sync.
+    fn evict_front(&mut self) {
+        if let Some((_, delta, seq)) = self.window.pop_front() {
+            self.sum -= delta;
+            if self.max_duration.front().map(|&(s, _)| s) == Some(seq) {
+                self.max_duration.pop_front();
+            }
+            if self.min_duration.front().map(|&(s, _)| s) == Some(seq) {
+                self.min_duration.pop_front();
+            }
+        }
+    }
+
     /// Calculate the frames per second from the current state of the window.
     fn calc_avg(&self) -> f64 {
-        let sum_secs = self.window.iter().map(|d| d.as_secs_f64()).sum::<f64>();
-        1.0 / (sum_secs / self.window.len() as f64)
+        1.0 / (self.sum.as_secs_f64() / self.window.len() as f64)
     }
 
     /// Find the minimum frames per second that occurs over the window.
     fn calc_min(&self) -> f64 {
         1.0 / self
-            .window
-            .iter()
-            .max()
-            .map(|d| d.as_secs_f64())
+            .max_duration
+            .front()
+            .map(|&(_, d)| d.as_secs_f64())
             .unwrap_or(0.0)
     }
 
     /// Find the minimum frames per second that occurs over the window.
     fn calc_max(&self) -> f64 {
         1.0 / self
-            .window
-            .iter()
-            .min()
-            .map(|d| d.as_secs_f64())
+            .min_duration
+            .front()
+            .map(|&(_, d)| d.as_secs_f64())
             .unwrap_or(0.0)
     }
 }
@@ -154,3 +487,173 @@ impl Default for Fps {
         Fps::with_window_len(Self::DEFAULT_WINDOW_LEN)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tiny deterministic PRNG (splitmix64) so tests don't need a `rand` dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next_u64(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        }
+
+        /// A millisecond count in `1..=max`.
+        fn next_millis(&mut self, max: u64) -> u64 {
+            1 + self.next_u64() % max
+        }
+    }
+
+    /// Drive a `tick` as if `delta` had elapsed since the previous one, without actually
+    /// sleeping, and return the exact duration `tick` recorded for it.
+    fn simulate_tick(fps: &Fps, delta: Duration) -> Duration {
+        fps.inner.borrow_mut().last = Instant::now() - delta;
+        fps.tick();
+        fps.inner
+            .borrow()
+            .window
+            .back()
+            .map(|&(_, d, _)| d)
+            .expect("tick always pushes an entry")
+    }
+
+    fn naive_avg(deltas: &[Duration]) -> f64 {
+        let sum_secs = deltas.iter().map(Duration::as_secs_f64).sum::<f64>();
+        1.0 / (sum_secs / deltas.len() as f64)
+    }
+
+    fn naive_min(deltas: &[Duration]) -> f64 {
+        1.0 / deltas.iter().max().unwrap().as_secs_f64()
+    }
+
+    fn naive_max(deltas: &[Duration]) -> f64 {
+        1.0 / deltas.iter().min().unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn incremental_stats_match_naive_recompute() {
+        let window_len = 8;
+        let fps = Fps::with_window_len(window_len);
+        let mut rng = Lcg(0x1234_5678_9abc_def0);
+        let mut deltas: Vec<Duration> = Vec::new();
+
+        for _ in 0..500 {
+            let delta = Duration::from_millis(rng.next_millis(50));
+            let recorded = simulate_tick(&fps, delta);
+
+            deltas.push(recorded);
+            if deltas.len() > window_len {
+                deltas.remove(0);
+            }
+
+            assert!((fps.avg() - naive_avg(&deltas)).abs() < 1e-6);
+            assert!((fps.min() - naive_min(&deltas)).abs() < 1e-6);
+            assert!((fps.max() - naive_max(&deltas)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn window_duration_evicts_expired_entries() {
+        let window_duration = Duration::from_millis(30);
+        let fps = Fps::with_window_duration(window_duration);
+
+        fps.tick();
+        fps.tick();
+        assert_eq!(fps.inner.borrow().window.len(), 2);
+
+        std::thread::sleep(window_duration * 2);
+        fps.tick();
+
+        // The first two ticks are now older than `window_duration` and should have been evicted,
+        // leaving only the tick just made.
+        assert_eq!(fps.inner.borrow().window.len(), 1);
+    }
+
+    /// Force the installed `report_every` callback to be considered due on the next `tick` by
+    /// backdating its `last` fire time, without any real sleeping.
+    fn backdate_report(fps: &Fps, by: Duration) {
+        if let Some(report) = fps.report.borrow_mut().as_mut() {
+            report.last = Instant::now() - by;
+        }
+    }
+
+    #[test]
+    fn report_every_fires_at_most_once_per_interval() {
+        let fps = Fps::with_window_len(8);
+        let count = Rc::new(RefCell::new(0u32));
+        let count_in_callback = Rc::clone(&count);
+        let interval = Duration::from_millis(100);
+        fps.report_every(interval, move |_| {
+            *count_in_callback.borrow_mut() += 1;
+        });
+
+        backdate_report(&fps, interval);
+        simulate_tick(&fps, Duration::from_millis(16));
+        assert_eq!(*count.borrow(), 1);
+
+        // Further ticks well within the interval must not fire again.
+        simulate_tick(&fps, Duration::from_millis(16));
+        simulate_tick(&fps, Duration::from_millis(16));
+        assert_eq!(*count.borrow(), 1);
+
+        // Once the interval has elapsed again, the next tick fires once more.
+        backdate_report(&fps, interval);
+        simulate_tick(&fps, Duration::from_millis(16));
+        assert_eq!(*count.borrow(), 2);
+    }
+
+    #[test]
+    fn report_every_callback_can_reenter_tick_without_panicking() {
+        let fps = Fps::with_window_len(8);
+        // An interval of zero means the very first tick is always due, so the callback fires
+        // immediately and its inner `tick()` exercises `fire_report`'s re-entrancy guard.
+        fps.report_every(Duration::default(), |fps| {
+            fps.tick();
+        });
+        fps.tick();
+    }
+
+    #[test]
+    fn tick_and_limit_returns_default_for_non_positive_target() {
+        let fps = Fps::with_target(8, 0.0);
+        assert_eq!(fps.tick_and_limit(), Duration::default());
+
+        let fps = Fps::with_target(8, -1.0);
+        assert_eq!(fps.tick_and_limit(), Duration::default());
+    }
+
+    #[test]
+    fn tick_and_limit_returns_default_when_over_budget() {
+        let fps = Fps::with_target(8, 1000.0);
+        fps.inner.borrow_mut().last = Instant::now() - Duration::from_millis(50);
+        assert_eq!(fps.tick_and_limit(), Duration::default());
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn percentile_low_matches_recorded_duration_and_reset_clears_it() {
+        let fps = Fps::with_window_len(8);
+        let delta = Duration::from_millis(16);
+        for _ in 0..10 {
+            simulate_tick(&fps, delta);
+        }
+
+        let expected_fps = 1.0 / delta.as_secs_f64();
+        let got = fps.percentile_low(50.0);
+        assert!(
+            (got - expected_fps).abs() / expected_fps < 0.01,
+            "got {}, expected ~{}",
+            got,
+            expected_fps
+        );
+
+        fps.reset();
+        assert_eq!(fps.percentile_low(50.0), 0.0);
+    }
+}